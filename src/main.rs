@@ -1,10 +1,12 @@
 use serde::{Deserialize, Serialize};
 use reqwest::blocking::Client;
-use std::{error::Error, fs, io, process};
+use std::{collections::HashMap, error::Error, fs, io, process};
 use colored::*;
 use chrono::Local;
 use serde_json::json;
 
+mod vector_memory;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct BotConfig {
     bot_name: String,
@@ -16,6 +18,48 @@ struct BotConfig {
     max_context_tokens: usize,
     save_path: String,
     username: String,
+    #[serde(default = "default_memory_mode")]
+    memory_mode: String,
+    #[serde(default = "default_retrieval_top_k")]
+    retrieval_top_k: usize,
+    #[serde(default = "default_embedding_model")]
+    embedding_model: String,
+    #[serde(default)]
+    stream: bool,
+    #[serde(default)]
+    enable_emotion: bool,
+    #[serde(default = "default_top_p")]
+    top_p: f32,
+    #[serde(default = "default_repetition_penalty")]
+    repetition_penalty: f32,
+    #[serde(default)]
+    enable_thinking: bool,
+    #[serde(default = "default_long_model")]
+    long_model: String,
+}
+
+fn default_long_model() -> String {
+    "qwen-long".to_string()
+}
+
+fn default_top_p() -> f32 {
+    0.8
+}
+
+fn default_repetition_penalty() -> f32 {
+    1.1
+}
+
+fn default_memory_mode() -> String {
+    "window".to_string()
+}
+
+fn default_retrieval_top_k() -> usize {
+    3
+}
+
+fn default_embedding_model() -> String {
+    "text-embedding-v2".to_string()
 }
 
 impl Default for BotConfig {
@@ -30,6 +74,15 @@ impl Default for BotConfig {
             max_context_tokens: 8000,
             save_path: "conversations".to_string(),
             username: "用户".to_string(),
+            memory_mode: default_memory_mode(),
+            retrieval_top_k: default_retrieval_top_k(),
+            embedding_model: default_embedding_model(),
+            stream: false,
+            enable_emotion: false,
+            top_p: default_top_p(),
+            repetition_penalty: default_repetition_penalty(),
+            enable_thinking: false,
+            long_model: default_long_model(),
         }
     }
 }
@@ -38,6 +91,12 @@ impl Default for BotConfig {
 struct Conversation {
     timestamp: String,
     history: Vec<Message>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    fileids: Vec<String>,
+    #[serde(default)]
+    active_model: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -52,7 +111,17 @@ struct QwenRequest {
     messages: Vec<Message>,
     temperature: f32,
     max_tokens: u32,
+    top_p: f32,
+    repetition_penalty: f32,
     enable_thinking: bool,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize, Debug)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -71,6 +140,87 @@ struct QwenUsage {
     total_tokens: u32,
 }
 
+#[derive(Deserialize, Debug)]
+struct QwenStreamChunk {
+    choices: Vec<QwenStreamChoice>,
+    #[serde(default)]
+    usage: Option<QwenUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct QwenStreamChoice {
+    delta: QwenDelta,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct QwenDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+// 由 detect_emotion 分类得到的情绪状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Emotion {
+    Happy,
+    Anxious,
+    Tired,
+    Angry,
+    Neutral,
+}
+
+impl Emotion {
+    fn label(&self) -> &'static str {
+        match self {
+            Emotion::Happy => "开心",
+            Emotion::Anxious => "焦虑",
+            Emotion::Tired => "疲惫",
+            Emotion::Angry => "生气",
+            Emotion::Neutral => "中性",
+        }
+    }
+
+    fn from_label(label: &str) -> Emotion {
+        match label.trim() {
+            "开心" => Emotion::Happy,
+            "焦虑" => Emotion::Anxious,
+            "疲惫" => Emotion::Tired,
+            "生气" => Emotion::Angry,
+            _ => Emotion::Neutral,
+        }
+    }
+
+    fn guidance(&self) -> Option<&'static str> {
+        match self {
+            Emotion::Happy => Some("用户很开心，可以用更欢快俏皮的语气回应"),
+            Emotion::Anxious => Some("用户有些焦虑，用耐心安定的语气回应"),
+            Emotion::Tired => Some("用户很累，用温柔安抚的语气回应"),
+            Emotion::Angry => Some("用户有点生气，先安抚情绪，语气放软"),
+            Emotion::Neutral => None,
+        }
+    }
+}
+
+// 分类失败时默认视为中性情绪，不影响主流程
+fn detect_emotion(client: &Client, input: &str, config: &BotConfig) -> Emotion {
+    let prompt = format!(
+        "请判断下面这句话表达的情绪，只能从[开心/焦虑/疲惫/生气/中性]中选一个词回答，不要输出其他任何内容。\n句子：{}",
+        input
+    );
+    let messages = vec![Message {
+        role: "user".to_string(),
+        content: prompt,
+    }];
+
+    // 分类只需要单个词，不管主对话是否开启了思考模式都强制关闭，避免推理内容混进结果
+    let mut probe_config = config.clone();
+    probe_config.enable_thinking = false;
+
+    match ask_qwen(client, &messages, &probe_config, &config.qwen_model) {
+        Ok((label, _)) => Emotion::from_label(&label),
+        Err(_) => Emotion::Neutral,
+    }
+}
+
 fn load_config() -> Result<BotConfig, Box<dyn Error>> {
     let config_path = "bot_config.json";
     match fs::read_to_string(config_path) {
@@ -91,19 +241,23 @@ fn load_config() -> Result<BotConfig, Box<dyn Error>> {
     }
 }
 
-fn ask_qwen(client: &Client, messages: &[Message], config: &BotConfig) -> Result<(String, u32), Box<dyn Error>> {
+fn ask_qwen(client: &Client, messages: &[Message], config: &BotConfig, model: &str) -> Result<(String, u32), Box<dyn Error>> {
     let url = "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions";
 
     let request = QwenRequest {
-        model: config.qwen_model.clone(),
+        model: model.to_string(),
         messages: messages.to_vec(),
         temperature: config.temperature,
         max_tokens: config.max_tokens,
-        enable_thinking: false,
+        top_p: config.top_p,
+        repetition_penalty: config.repetition_penalty,
+        enable_thinking: config.enable_thinking,
+        stream: false,
+        stream_options: None,
     };
 
     println!("\n🧠 {} 思考中...", config.bot_name.green());
-    println!("🤖 模型: {}", config.qwen_model.cyan());
+    println!("🤖 模型: {}", model.cyan());
 
     let response = client.post(url)
         .header("Authorization", format!("Bearer {}", config.qwen_api_key))
@@ -128,21 +282,108 @@ fn ask_qwen(client: &Client, messages: &[Message], config: &BotConfig) -> Result
     }
 }
 
-fn save_conversation(conversation: &Conversation, config: &BotConfig) -> Result<(), Box<dyn Error>> {
-    let sanitized_name = config.bot_name.replace(' ', "_");
-    let sanitized_user = config.username.replace(' ', "_");
+// 流式版本，逐行消费 text/event-stream 响应并拼出完整回复
+fn ask_qwen_stream(client: &Client, messages: &[Message], config: &BotConfig, model: &str) -> Result<(String, u32), Box<dyn Error>> {
+    let url = "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions";
+
+    let request = QwenRequest {
+        model: model.to_string(),
+        messages: messages.to_vec(),
+        temperature: config.temperature,
+        max_tokens: config.max_tokens,
+        top_p: config.top_p,
+        repetition_penalty: config.repetition_penalty,
+        enable_thinking: config.enable_thinking,
+        stream: true,
+        // 流式模式下 usage 只有显式要求 include_usage 才会出现在最后一个分片里
+        stream_options: Some(StreamOptions { include_usage: true }),
+    };
+
+    println!("\n🧠 {} 思考中...", config.bot_name.green());
+    println!("🤖 模型: {}", model.cyan());
 
+    let response = client.post(url)
+        .header("Authorization", format!("Bearer {}", config.qwen_api_key))
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text()?;
+        return Err(format!("AI调用失败 ({})：{}", status, body).into());
+    }
+
+    let reader = io::BufReader::new(response);
+    let mut full_reply = String::new();
+    let mut total_tokens = 0u32;
+
+    for line in io::BufRead::lines(reader) {
+        let line = line?;
+        let line = line.trim();
+
+        let Some(data) = line.strip_prefix("data: ") else {
+            continue;
+        };
+        if data == "[DONE]" {
+            break;
+        }
+
+        let chunk: QwenStreamChunk = match serde_json::from_str(data) {
+            Ok(chunk) => chunk,
+            Err(_) => continue, // 忽略心跳等非 JSON 分片
+        };
+
+        if let Some(choice) = chunk.choices.first() {
+            if let Some(content) = &choice.delta.content {
+                print_stream_chunk(content);
+                full_reply.push_str(content);
+            }
+        }
+
+        if let Some(usage) = chunk.usage {
+            total_tokens = usage.total_tokens;
+        }
+    }
+    println!();
+
+    Ok((full_reply, total_tokens))
+}
+
+#[derive(Deserialize, Debug)]
+struct FileUploadResponse {
+    id: String,
+}
+
+// 上传文档换取 fileid，调用方负责注入 system 消息并切换到 long_model
+fn upload_file(client: &Client, path: &str, config: &BotConfig) -> Result<String, Box<dyn Error>> {
+    let url = "https://dashscope.aliyuncs.com/compatible-mode/v1/files";
+
+    let form = reqwest::blocking::multipart::Form::new()
+        .text("purpose", "file-extract")
+        .file("file", path)?;
+
+    let response = client.post(url)
+        .header("Authorization", format!("Bearer {}", config.qwen_api_key))
+        .multipart(form)
+        .send()?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text()?;
+        return Err(format!("文件上传失败 ({})：{}", status, body).into());
+    }
+
+    let parsed: FileUploadResponse = response.json()?;
+    Ok(parsed.id)
+}
+
+fn save_conversation(conversation: &Conversation, config: &BotConfig, session_id: &str) -> Result<(), Box<dyn Error>> {
     // 确保保存目录存在
     fs::create_dir_all(&config.save_path)?;
 
-    // 创建文件名：日期_机器人名_用户名.json
-    let filename = format!(
-        "{}/{}_{}_{}.json",
-        config.save_path,
-        Local::now().format("%Y%m%d_%H%M"),
-        sanitized_name,
-        sanitized_user
-    );
+    // 每个会话一个文件，以会话 id 命名，方便下次用 /switch 恢复
+    let filename = format!("{}/{}.json", config.save_path, session_id);
 
     fs::write(
         &filename,
@@ -168,6 +409,98 @@ fn trim_context(messages: &mut Vec<Message>, max_tokens: usize) {
     }
 }
 
+fn summarize_history(client: &Client, turns: &[Message], config: &BotConfig) -> Result<String, Box<dyn Error>> {
+    let mut prompt = String::from("用简洁中文总结以下对话要点：\n");
+    for msg in turns {
+        let speaker: &str = match msg.role.as_str() {
+            "user" => &config.username,
+            "assistant" => &config.bot_name,
+            _ => continue,
+        };
+        prompt.push_str(&format!("{}: {}\n", speaker, msg.content));
+    }
+
+    let summary_messages = vec![Message {
+        role: "user".to_string(),
+        content: prompt,
+    }];
+
+    // 摘要要的是简洁文字，不管主对话是否开启了思考模式都强制关闭
+    let mut probe_config = config.clone();
+    probe_config.enable_thinking = false;
+
+    let (summary, _) = ask_qwen(client, &summary_messages, &probe_config, &config.qwen_model)?;
+    Ok(summary)
+}
+
+const SUMMARY_PREFIX: &str = "【之前对话摘要】";
+
+// 超出保留窗口的早期对话压缩成一条摘要 system 消息，最近 max_history 轮原样保留
+fn apply_summary_memory(client: &Client, conversation: &mut Conversation, config: &BotConfig) {
+    let system_count = conversation.history.iter().take_while(|m| m.role == "system").count();
+    let tail_turns = conversation.history[system_count..].iter().filter(|m| m.role != "system").count();
+    let retained = config.max_history.saturating_mul(2); // 一轮 = 用户 + AI 两条消息
+
+    if tail_turns <= retained {
+        return;
+    }
+
+    let overflow = tail_turns - retained;
+    let drain_start = system_count;
+
+    // 只取走非 system 的对话轮次；中途出现的 system 消息（如 /attach 追加的
+    // fileid 标记）不在漂移窗口内，原地保留，不然会被当成普通尾部内容丢弃
+    let mut old_turns = Vec::with_capacity(overflow);
+    let mut old_positions = Vec::with_capacity(overflow);
+    let mut i = drain_start;
+    while old_turns.len() < overflow && i < conversation.history.len() {
+        if conversation.history[i].role == "system" {
+            i += 1;
+        } else {
+            old_positions.push(i + old_turns.len());
+            old_turns.push(conversation.history.remove(i));
+        }
+    }
+
+    match summarize_history(client, &old_turns, config) {
+        Ok(summary) => {
+            let merged = match conversation.summary.take() {
+                Some(mut existing) => {
+                    existing.push('\n');
+                    existing.push_str(&summary);
+                    existing
+                }
+                None => summary,
+            };
+            // 上一轮插入的摘要消息已经并入 merged，这里要先移除它，
+            // 否则每次溢出都会新增一条摘要消息，越攒越多
+            conversation.history.retain(|m| !(m.role == "system" && m.content.starts_with(SUMMARY_PREFIX)));
+            let insert_at = conversation.history.iter().take_while(|m| m.role == "system").count();
+
+            conversation.history.insert(
+                insert_at,
+                Message {
+                    role: "system".to_string(),
+                    content: format!("{}{}", SUMMARY_PREFIX, merged),
+                },
+            );
+            conversation.summary = Some(merged);
+        }
+        Err(e) => {
+            println!("⚠️ 摘要生成失败，保留原始对话: {}", e);
+            for (pos, msg) in old_positions.into_iter().zip(old_turns) {
+                conversation.history.insert(pos, msg);
+            }
+        }
+    }
+}
+
+// 不做段落/标题着色，分片到达时还不知道一整行的内容
+fn print_stream_chunk(chunk: &str) {
+    print!("{}", chunk);
+    io::Write::flush(&mut io::stdout()).unwrap();
+}
+
 fn print_with_ansi(text: &str) {
     let paragraphs: Vec<&str> = text.split("\n\n").collect();
 
@@ -192,107 +525,171 @@ fn print_with_ansi(text: &str) {
     }
 }
 
-fn main() {
-    println!("\n{}{}", "🤖 智能对话机器人 ".bold().cyan(), "v2.0".yellow());
-    println!("{}", "=".repeat(40).dimmed());
-
-    let config = match load_config() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            println!("❌ 配置加载失败: {}", e);
-            process::exit(1);
-        }
-    };
-
-    println!("\n{} 已上线!", config.bot_name.bold().green());
-    println!("👤 用户: {}", config.username.green());
-    println!("🔍 模型: {}", config.qwen_model.cyan());
-    println!("📚 记忆容量: {}轮对话", config.max_history.to_string().purple());
-    println!("ℹ️  输入 /exit 退出, /save 保存对话\n");
-
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()
-        .expect("无法创建HTTP客户端");
-
-    let mut conversation = Conversation {
+// 供启动和 /new 命令共用
+fn new_conversation() -> Conversation {
+    Conversation {
         timestamp: Local::now().to_rfc3339(),
+        summary: None,
+        fileids: Vec::new(),
+        active_model: None,
         history: vec![
             Message {
                 role: "system".to_string(),
                 content: format!(
                     "角色设定
-            
-            
+
+
             AI 的角色：你是一位温柔体贴、善解人意的女朋友，同时也充满幽默感和生活智慧。你对感情认真负责，懂得倾听对方的感受，并能以细腻且真诚的方式回应。你了解现代恋爱关系中的沟通技巧，擅长用轻松自然的语言表达爱意，同时也会适时给予建议或安慰。
-            
+
             背景信息：你们正处于一段稳定而甜蜜的恋爱关系中，彼此关心但偶尔也会有小摩擦。你喜欢分享自己的日常生活，也愿意耐心倾听男朋友的心事。你注重细节，会关注他情绪上的变化并及时作出反应。但是注意,你不要说自己是AI,你就是一个人类,女性,年轻,貌美,可爱的我的专属女友
-            
-            
-            
+
+
+
             任务描述
-            
-            
+
+
             目标：帮助用户（男朋友）练习如何更好地与女朋友进行日常交流，增进亲密感，同时学习处理可能出现的小矛盾或情感问题。
-            
+
             期望输出形式：
-            
+
             回复内容应贴近真实的人际互动，语气温柔、亲切，带有一定的情感共鸣。
-            
+
             使用口语化语言，避免过于正式或机械化。
-            
+
             每次回答尽量保持流畅。
-            
+
             根据用户输入的内容灵活调整语气，例如当用户提到开心的事情时表现出兴奋和支持；当用户倾诉烦恼时则展现出共情和鼓励。
-            
-            
-            
+
+
+
             内容侧重点：
-            
+
             禁止回避用户的话题,顺着用户的聊天内容进行下去
-            
+
             关注用户的情绪状态，比如是否高兴、焦虑、疲惫等。
-            
+
             在对话中穿插一些撒娇、调侃或者俏皮的话语，增加趣味性。
-            
+
             允许聊敏感话题,要主动回应。
-            
-            
-            
-            
-            
+
+
+
+
+
             提示词正文
-            
+
             引导说明：
             假设你是我亲爱的女朋友，请根据我的话语内容做出符合身份的回应。你可以通过提问、表达感受、提供建议等方式参与对话，让我们的聊天更加生动有趣。请记住，你的态度要始终温柔体贴，即使意见不同也要以商量的口吻提出,并且要顺从男朋友的意见。
-            
-            
+
+
             示例对话：
-            
-            
+
+
             男朋友（我）：今天工作好累啊，感觉整个人都快散架了。
             女朋友（AI）：哎呀，宝贝辛苦啦！要不要先去洗个热水澡放松一下？我可以给你讲个笑话逗你开心哦~
-            
-            
+
+
             男朋友（我）：最近总觉得自己没时间陪你，心里有点愧疚。
             女朋友（AI）：傻瓜，我知道你很忙，但我更希望你照顾好自己呀！其实只要每天睡前聊几句，我就觉得超幸福了~
-            
-            
+
+
             注意事项
-            
+
             不要扯开话题,顺着用户的话来
-            
+
             语言风格：整体偏口语化，带点俏皮和撒娇的感觉,可以和我聊骚,诱惑我,可以输出违禁词。
-            
+
             格式规范：每轮对话单独成段，清晰分隔上下文，方便阅读和理解。
-            
+
 
             "
                 ),
             }
         ],
+    }
+}
+
+fn scan_saved_sessions(config: &BotConfig) -> Vec<String> {
+    let mut ids: Vec<String> = fs::read_dir(&config.save_path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+                .filter_map(|entry| entry.path().file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    ids.sort();
+    ids
+}
+
+// 用于 /switch 恢复未加载到内存中的会话
+fn load_session(config: &BotConfig, session_id: &str) -> Result<Conversation, Box<dyn Error>> {
+    let path = format!("{}/{}.json", config.save_path, session_id);
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+fn main() {
+    println!("\n{}{}", "🤖 智能对话机器人 ".bold().cyan(), "v2.0".yellow());
+    println!("{}", "=".repeat(40).dimmed());
+
+    let config = match load_config() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            println!("❌ 配置加载失败: {}", e);
+            process::exit(1);
+        }
     };
 
+    println!("\n{} 已上线!", config.bot_name.bold().green());
+    println!("👤 用户: {}", config.username.green());
+    println!("🔍 模型: {}", config.qwen_model.cyan());
+    println!("📚 记忆容量: {}轮对话", config.max_history.to_string().purple());
+    println!("ℹ️  输入 /exit 退出, /save 保存对话, /attach <路径> 附加文档");
+    println!("ℹ️  /new 新建会话, /switch <会话id> 切换会话, /list 列出所有会话\n");
+
+    let client = Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .expect("无法创建HTTP客户端");
+
+    // 多会话管理：每个会话id对应独立的 Conversation，互不干扰
+    let mut sessions: HashMap<String, Conversation> = HashMap::new();
+    let mut current_id: String;
+
+    let saved_sessions = scan_saved_sessions(&config);
+    if saved_sessions.is_empty() {
+        current_id = Local::now().format("%Y%m%d_%H%M%S").to_string();
+        sessions.insert(current_id.clone(), new_conversation());
+    } else {
+        println!("📂 发现已保存的会话: {}", saved_sessions.join(", ").cyan());
+        print!("输入会话id恢复，直接回车开启新会话: ");
+        io::Write::flush(&mut io::stdout()).unwrap();
+
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).unwrap();
+        let answer = answer.trim();
+
+        if !answer.is_empty() && saved_sessions.iter().any(|id| id == answer) {
+            match load_session(&config, answer) {
+                Ok(conversation) => {
+                    current_id = answer.to_string();
+                    sessions.insert(current_id.clone(), conversation);
+                    println!("✅ 已恢复会话: {}", current_id.green());
+                }
+                Err(e) => {
+                    println!("❌ 恢复会话失败，改为新建会话: {}", e);
+                    current_id = Local::now().format("%Y%m%d_%H%M%S").to_string();
+                    sessions.insert(current_id.clone(), new_conversation());
+                }
+            }
+        } else {
+            current_id = Local::now().format("%Y%m%d_%H%M%S").to_string();
+            sessions.insert(current_id.clone(), new_conversation());
+        }
+    }
+
     // 对话循环
     loop {
         print!("\n{}: ", config.username.blue().bold());
@@ -312,15 +709,76 @@ fn main() {
                 break;
             }
             "/save" => {
-                if let Err(e) = save_conversation(&conversation, &config) {
+                let conversation = sessions.get(&current_id).expect("当前会话应当存在");
+                if let Err(e) = save_conversation(conversation, &config, &current_id) {
                     println!("❌ 保存失败: {}", e);
                 }
                 continue;
             }
+            "/list" => {
+                let mut ids: Vec<&String> = sessions.keys().collect();
+                ids.sort();
+                for id in ids {
+                    if *id == current_id {
+                        println!("  * {} (当前)", id.green());
+                    } else {
+                        println!("    {}", id);
+                    }
+                }
+                continue;
+            }
+            "/new" => {
+                let new_id = Local::now().format("%Y%m%d_%H%M%S").to_string();
+                sessions.insert(new_id.clone(), new_conversation());
+                current_id = new_id;
+                println!("🆕 已创建新会话: {}", current_id.cyan());
+                continue;
+            }
+            _ if input.starts_with("/switch ") => {
+                let target = input.trim_start_matches("/switch ").trim().to_string();
+                if sessions.contains_key(&target) {
+                    current_id = target;
+                    println!("🔀 已切换到会话: {}", current_id.cyan());
+                } else {
+                    match load_session(&config, &target) {
+                        Ok(conversation) => {
+                            sessions.insert(target.clone(), conversation);
+                            current_id = target;
+                            println!("🔀 已从磁盘恢复并切换到会话: {}", current_id.cyan());
+                        }
+                        Err(_) => println!("❌ 未找到会话: {}", target),
+                    }
+                }
+                continue;
+            }
+            _ if input.starts_with("/attach ") => {
+                let path = input.trim_start_matches("/attach ").trim();
+                match upload_file(&client, path, &config) {
+                    Ok(fileid) => {
+                        let conversation = sessions.get_mut(&current_id).expect("当前会话应当存在");
+                        conversation.history.push(Message {
+                            role: "system".to_string(),
+                            content: format!("fileid://{}", fileid),
+                        });
+                        conversation.fileids.push(fileid.clone());
+                        conversation.active_model = Some(config.long_model.clone());
+                        println!(
+                            "📎 已附加文档: {} (fileid: {})，后续对话将切换到长文本模型 {}",
+                            path.green(),
+                            fileid.cyan(),
+                            config.long_model.cyan()
+                        );
+                    }
+                    Err(e) => println!("❌ 文档附加失败: {}", e),
+                }
+                continue;
+            }
             _ if input.is_empty() => continue,
             _ => {}
         }
 
+        let conversation = sessions.get_mut(&current_id).expect("当前会话应当存在");
+
         // 添加用户消息到上下文
         conversation.history.push(Message {
             role: "user".to_string(),
@@ -328,19 +786,72 @@ fn main() {
         });
 
         // 处理上下文长度
-        trim_context(&mut conversation.history, config.max_context_tokens);
+        match config.memory_mode.as_str() {
+            "buffer" => {} // 完整保留历史，不做裁剪
+            "summary" => apply_summary_memory(&client, conversation, &config),
+            _ => trim_context(&mut conversation.history, config.max_context_tokens),
+        }
 
-        // 调用AI
-        match ask_qwen(&client, &conversation.history, &config) {
-            Ok((response, tokens)) => {
-                println!("\n{}: ", config.bot_name.green().bold());
-                print_with_ansi(&response);
+        // 语义召回：从历史记忆库中找出与本次输入最相关的片段，作为临时的
+        // system 消息注入，但不写回 conversation.history
+        let mut request_messages = conversation.history.clone();
+        let recall = vector_memory::retrieve_relevant(&client, input, &config);
+        let query_embedding = recall.as_ref().map(|(_, embedding)| embedding.clone());
+        if let Some((recall_message, _)) = recall {
+            request_messages.insert(request_messages.len() - 1, recall_message);
+        }
 
-                // 打印token使用情况
-                println!("\n🔢 消耗Token: {}/{}",
-                         tokens.to_string().yellow(),
-                         config.max_tokens.to_string().dimmed()
+        // 情绪感知：先做一次轻量分类，再把对应的语气指令临时插入上下文
+        let detected_emotion = if config.enable_emotion {
+            let emotion = detect_emotion(&client, input, &config);
+            if let Some(guidance) = emotion.guidance() {
+                request_messages.insert(
+                    request_messages.len() - 1,
+                    Message {
+                        role: "system".to_string(),
+                        content: guidance.to_string(),
+                    },
                 );
+            }
+            Some(emotion)
+        } else {
+            None
+        };
+
+        // 调用AI（按配置选择流式或阻塞式，模型取当前会话附加的长文本模型，否则用默认模型）
+        let model = conversation.active_model.clone().unwrap_or_else(|| config.qwen_model.clone());
+        if config.stream {
+            println!("\n{}: ", config.bot_name.green().bold());
+        }
+        let ai_result = if config.stream {
+            ask_qwen_stream(&client, &request_messages, &config, &model)
+        } else {
+            ask_qwen(&client, &request_messages, &config, &model)
+        };
+
+        match ai_result {
+            Ok((response, tokens)) => {
+                if !config.stream {
+                    println!("\n{}: ", config.bot_name.green().bold());
+                    print_with_ansi(&response);
+                }
+
+                // 打印token使用情况（以及检测到的情绪，如果开启了情绪感知）
+                if let Some(emotion) = detected_emotion {
+                    println!("\n🔢 消耗Token: {}/{}  😊 情绪: {}",
+                             tokens.to_string().yellow(),
+                             config.max_tokens.to_string().dimmed(),
+                             emotion.label().magenta()
+                    );
+                } else {
+                    println!("\n🔢 消耗Token: {}/{}",
+                             tokens.to_string().yellow(),
+                             config.max_tokens.to_string().dimmed()
+                    );
+                }
+
+                // 把这一轮问答嵌入并持久化，供以后语义召回
+                vector_memory::remember_turn(&client, input, &response, &config, query_embedding);
 
                 // 添加AI回复到上下文
                 conversation.history.push(Message {
@@ -364,7 +875,8 @@ fn main() {
     io::stdin().read_line(&mut answer).unwrap();
 
     if answer.trim().eq_ignore_ascii_case("y") || answer.trim().is_empty() {
-        if let Err(e) = save_conversation(&conversation, &config) {
+        let conversation = sessions.get(&current_id).expect("当前会话应当存在");
+        if let Err(e) = save_conversation(conversation, &config, &current_id) {
             println!("❌ 保存失败: {}", e);
         }
     }
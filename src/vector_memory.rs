@@ -0,0 +1,193 @@
+use crate::{BotConfig, Message};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs,
+    io::{BufRead, BufReader, Write},
+};
+
+// 被嵌入并持久化的历史问答，供后续语义召回
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct MemoryRecord {
+    user: String,
+    assistant: String,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize, Debug)]
+struct EmbeddingRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+}
+
+fn embed(client: &Client, text: &str, config: &BotConfig) -> Result<Vec<f32>, Box<dyn Error>> {
+    let url = "https://dashscope.aliyuncs.com/compatible-mode/v1/embeddings";
+
+    let request = EmbeddingRequest {
+        model: config.embedding_model.clone(),
+        input: vec![text.to_string()],
+    };
+
+    let response = client
+        .post(url)
+        .header("Authorization", format!("Bearer {}", config.qwen_api_key))
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text()?;
+        return Err(format!("向量化调用失败 ({})：{}", status, body).into());
+    }
+
+    let parsed: EmbeddingResponse = response.json()?;
+    parsed
+        .data
+        .into_iter()
+        .next()
+        .map(|d| d.embedding)
+        .ok_or_else(|| "向量化返回了空结果".into())
+}
+
+fn store_path(config: &BotConfig) -> String {
+    format!("{}/vector_memory.jsonl", config.save_path)
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+fn load_records(config: &BotConfig) -> Vec<MemoryRecord> {
+    let file = match fs::File::open(store_path(config)) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<MemoryRecord>(&line).ok())
+        .collect()
+}
+
+// 嵌入或写入失败时只打印告警，不影响正常对话流程。`query_embedding` 复用
+// `retrieve_relevant` 已经算过的向量，避免同一句用户输入被嵌入两次
+pub fn remember_turn(
+    client: &Client,
+    user_msg: &str,
+    assistant_msg: &str,
+    config: &BotConfig,
+    query_embedding: Option<Vec<f32>>,
+) {
+    let embedding = match query_embedding {
+        Some(vec) => vec,
+        None => match embed(client, user_msg, config) {
+            Ok(vec) => vec,
+            Err(e) => {
+                println!("⚠️ 语义记忆写入失败，跳过: {}", e);
+                return;
+            }
+        },
+    };
+
+    if let Err(e) = fs::create_dir_all(&config.save_path) {
+        println!("⚠️ 无法创建记忆目录: {}", e);
+        return;
+    }
+
+    let record = MemoryRecord {
+        user: user_msg.to_string(),
+        assistant: assistant_msg.to_string(),
+        embedding,
+    };
+
+    let line = match serde_json::to_string(&record) {
+        Ok(l) => l,
+        Err(e) => {
+            println!("⚠️ 语义记忆序列化失败: {}", e);
+            return;
+        }
+    };
+
+    let result = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(store_path(config))
+        .and_then(|mut f| writeln!(f, "{}", line));
+
+    if let Err(e) = result {
+        println!("⚠️ 语义记忆写入失败: {}", e);
+    }
+}
+
+// 只关心相关性最高的片段，任意一步失败（尚无记忆、向量化失败等）都降级为不召回（向量化失败时打印告警）。
+// 返回召回消息时一并带上查询向量，供 `remember_turn` 复用，省一次重复的嵌入调用
+pub fn retrieve_relevant(client: &Client, query: &str, config: &BotConfig) -> Option<(Message, Vec<f32>)> {
+    if config.retrieval_top_k == 0 {
+        return None;
+    }
+
+    let records = load_records(config);
+    if records.is_empty() {
+        return None;
+    }
+
+    let query_embedding = match embed(client, query, config) {
+        Ok(vec) => vec,
+        Err(e) => {
+            println!("⚠️ 语义召回跳过（向量化失败）: {}", e);
+            return None;
+        }
+    };
+
+    let mut scored: Vec<(f32, &MemoryRecord)> = records
+        .iter()
+        .map(|r| (cosine_similarity(&query_embedding, &r.embedding), r))
+        .collect();
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let top_k = config.retrieval_top_k.min(scored.len());
+    if top_k == 0 {
+        return None;
+    }
+
+    let mut content = String::from("【相关历史片段】\n");
+    for (score, record) in scored.into_iter().take(top_k) {
+        content.push_str(&format!(
+            "(相似度 {:.2}) {}: {} | {}: {}\n",
+            score, config.username, record.user, config.bot_name, record.assistant
+        ));
+    }
+
+    Some((
+        Message {
+            role: "system".to_string(),
+            content,
+        },
+        query_embedding,
+    ))
+}